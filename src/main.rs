@@ -1,18 +1,240 @@
+use std::fmt;
 use std::ptr;
 use winapi::shared::guiddef::*;
 use winapi::shared::winerror::S_OK;
 use winapi::shared::winerror::*;
+use winapi::shared::wtypes::BSTR;
 use winapi::um::combaseapi::StringFromGUID2;
 use winapi::um::combaseapi::*;
 use winapi::um::objbase::*;
+use winapi::um::oleauto::SysFreeString;
 use winapi::um::vsbackup::*;
 use winapi::um::vss::*;
-use winapi::um::winbase::INFINITE;
+use winapi::um::winbase::{
+    FormatMessageW, LocalFree, FORMAT_MESSAGE_ALLOCATE_BUFFER, FORMAT_MESSAGE_FROM_SYSTEM,
+    FORMAT_MESSAGE_IGNORE_INSERTS, INFINITE,
+};
 
 fn hresult_to_hex(hr: i32) -> String {
     format!("0x{:08X}", hr) // Format the HRESULT as an 8-digit hexadecimal
 }
 
+/// Looks up the system-provided message for an `HRESULT` via
+/// `FormatMessageW`, for codes we don't special-case ourselves.
+fn system_message(hr: HRESULT) -> String {
+    unsafe {
+        let mut buffer: *mut u16 = ptr::null_mut();
+        let len = FormatMessageW(
+            FORMAT_MESSAGE_ALLOCATE_BUFFER | FORMAT_MESSAGE_FROM_SYSTEM | FORMAT_MESSAGE_IGNORE_INSERTS,
+            ptr::null(),
+            hr as u32,
+            0,
+            &mut buffer as *mut *mut u16 as *mut u16,
+            0,
+            ptr::null_mut(),
+        );
+
+        if len == 0 || buffer.is_null() {
+            return String::from("Unknown error");
+        }
+
+        let message = pwsz_to_string(buffer);
+        LocalFree(buffer as *mut _);
+        message.trim_end().to_string()
+    }
+}
+
+/// Describes an `HRESULT` for logging/diagnostics: the well-known VSS async
+/// statuses get a short descriptive phrase, anything else falls back to the
+/// system's own message, and the raw hex code is always appended, e.g.
+/// `"VSS async operation cancelled (0x0004230B)"`.
+fn describe_hresult(hr: HRESULT) -> String {
+    let summary = match hr {
+        VSS_S_ASYNC_CANCELLED => String::from("VSS async operation cancelled"),
+        VSS_S_ASYNC_FINISHED => String::from("VSS async operation finished"),
+        VSS_S_ASYNC_PENDING => String::from("VSS async operation still pending"),
+        _ => system_message(hr),
+    };
+
+    format!("{} ({})", summary, hresult_to_hex(hr))
+}
+
+/// Decoded form of the `state` out-param of `GetWriterStatus`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VssWriterState {
+    Unknown,
+    Stable,
+    WaitingForFreeze,
+    WaitingForThaw,
+    WaitingForPostSnapshot,
+    WaitingForBackupComplete,
+    FailedAtIdentify,
+    FailedAtPrepareBackup,
+    FailedAtPrepareSnapshot,
+    FailedAtFreeze,
+    FailedAtThaw,
+    FailedAtPostSnapshot,
+    FailedAtBackupComplete,
+    FailedAtPreRestore,
+    FailedAtPostRestore,
+    FailedAtBackupShutdown,
+    Other(VSS_WRITER_STATE),
+}
+
+impl VssWriterState {
+    fn from_raw(state: VSS_WRITER_STATE) -> Self {
+        match state {
+            VSS_WS_UNKNOWN => VssWriterState::Unknown,
+            VSS_WS_STABLE => VssWriterState::Stable,
+            VSS_WS_WAITING_FOR_FREEZE => VssWriterState::WaitingForFreeze,
+            VSS_WS_WAITING_FOR_THAW => VssWriterState::WaitingForThaw,
+            VSS_WS_WAITING_FOR_POST_SNAPSHOT => VssWriterState::WaitingForPostSnapshot,
+            VSS_WS_WAITING_FOR_BACKUP_COMPLETE => VssWriterState::WaitingForBackupComplete,
+            VSS_WS_FAILED_AT_IDENTIFY => VssWriterState::FailedAtIdentify,
+            VSS_WS_FAILED_AT_PREPARE_BACKUP => VssWriterState::FailedAtPrepareBackup,
+            VSS_WS_FAILED_AT_PREPARE_SNAPSHOT => VssWriterState::FailedAtPrepareSnapshot,
+            VSS_WS_FAILED_AT_FREEZE => VssWriterState::FailedAtFreeze,
+            VSS_WS_FAILED_AT_THAW => VssWriterState::FailedAtThaw,
+            VSS_WS_FAILED_AT_POST_SNAPSHOT => VssWriterState::FailedAtPostSnapshot,
+            VSS_WS_FAILED_AT_BACKUP_COMPLETE => VssWriterState::FailedAtBackupComplete,
+            VSS_WS_FAILED_AT_PRE_RESTORE => VssWriterState::FailedAtPreRestore,
+            VSS_WS_FAILED_AT_POST_RESTORE => VssWriterState::FailedAtPostRestore,
+            VSS_WS_FAILED_AT_BACKUPSHUTDOWN => VssWriterState::FailedAtBackupShutdown,
+            other => VssWriterState::Other(other),
+        }
+    }
+
+    /// Whether this state represents a writer that failed somewhere in the
+    /// backup (or restore) sequence.
+    pub fn is_failed(self) -> bool {
+        matches!(
+            self,
+            VssWriterState::FailedAtIdentify
+                | VssWriterState::FailedAtPrepareBackup
+                | VssWriterState::FailedAtPrepareSnapshot
+                | VssWriterState::FailedAtFreeze
+                | VssWriterState::FailedAtThaw
+                | VssWriterState::FailedAtPostSnapshot
+                | VssWriterState::FailedAtBackupComplete
+                | VssWriterState::FailedAtPreRestore
+                | VssWriterState::FailedAtPostRestore
+                | VssWriterState::FailedAtBackupShutdown
+        )
+    }
+}
+
+impl fmt::Display for VssWriterState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VssWriterState::Unknown => write!(f, "unknown"),
+            VssWriterState::Stable => write!(f, "stable"),
+            VssWriterState::WaitingForFreeze => write!(f, "waiting for freeze"),
+            VssWriterState::WaitingForThaw => write!(f, "waiting for thaw"),
+            VssWriterState::WaitingForPostSnapshot => write!(f, "waiting for post-snapshot"),
+            VssWriterState::WaitingForBackupComplete => write!(f, "waiting for backup complete"),
+            VssWriterState::FailedAtIdentify => write!(f, "failed at identify"),
+            VssWriterState::FailedAtPrepareBackup => write!(f, "failed at prepare backup"),
+            VssWriterState::FailedAtPrepareSnapshot => write!(f, "failed at prepare snapshot"),
+            VssWriterState::FailedAtFreeze => write!(f, "failed at freeze"),
+            VssWriterState::FailedAtThaw => write!(f, "failed at thaw"),
+            VssWriterState::FailedAtPostSnapshot => write!(f, "failed at post-snapshot"),
+            VssWriterState::FailedAtBackupComplete => write!(f, "failed at backup complete"),
+            VssWriterState::FailedAtPreRestore => write!(f, "failed at pre-restore"),
+            VssWriterState::FailedAtPostRestore => write!(f, "failed at post-restore"),
+            VssWriterState::FailedAtBackupShutdown => write!(f, "failed at backup shutdown"),
+            VssWriterState::Other(state) => write!(f, "unrecognized state ({})", state),
+        }
+    }
+}
+
+/// Decoded form of the `failure_reason` out-param of `GetWriterStatus`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VssWriterFailure {
+    None,
+    InconsistentSnapshot,
+    OutOfResources,
+    Timeout,
+    Retryable,
+    NonRetryable,
+    RecoveryFailed,
+    Other(HRESULT),
+}
+
+impl VssWriterFailure {
+    fn from_raw(hr: HRESULT) -> Self {
+        match hr {
+            S_OK => VssWriterFailure::None,
+            VSS_E_WRITERERROR_INCONSISTENTSNAPSHOT => VssWriterFailure::InconsistentSnapshot,
+            VSS_E_WRITERERROR_OUTOFRESOURCES => VssWriterFailure::OutOfResources,
+            VSS_E_WRITERERROR_TIMEOUT => VssWriterFailure::Timeout,
+            VSS_E_WRITERERROR_RETRYABLE => VssWriterFailure::Retryable,
+            VSS_E_WRITERERROR_NONRETRYABLE => VssWriterFailure::NonRetryable,
+            VSS_E_WRITERERROR_RECOVERY_FAILED => VssWriterFailure::RecoveryFailed,
+            other => VssWriterFailure::Other(other),
+        }
+    }
+}
+
+impl fmt::Display for VssWriterFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VssWriterFailure::None => write!(f, "no failure"),
+            VssWriterFailure::InconsistentSnapshot => write!(f, "inconsistent snapshot"),
+            VssWriterFailure::OutOfResources => write!(f, "out of resources"),
+            VssWriterFailure::Timeout => write!(f, "timeout"),
+            VssWriterFailure::Retryable => write!(f, "retryable error"),
+            VssWriterFailure::NonRetryable => write!(f, "non-retryable error"),
+            VssWriterFailure::RecoveryFailed => write!(f, "recovery failed"),
+            VssWriterFailure::Other(hr) => write!(f, "{}", describe_hresult(*hr)),
+        }
+    }
+}
+
+/// Errors produced by the VSS helpers in this crate.
+#[derive(Debug)]
+enum VssError {
+    /// `wait_for_async` was handed a null `IVssAsync` pointer.
+    NullAsync,
+    /// A VSS/COM call returned a failing (or, for async status, a
+    /// non-successful) `HRESULT`.
+    Failed(HRESULT),
+    /// A writer reported a failed state; a backup should not proceed while
+    /// this is true.
+    WriterFailed {
+        writer_id: String,
+        writer_name: String,
+        state: VssWriterState,
+        reason: VssWriterFailure,
+    },
+    /// [`SnapshotSession::shadow_path_for`] was asked about a path whose
+    /// volume isn't covered by any snapshot in the session.
+    NoMatchingSnapshot(String),
+}
+
+impl fmt::Display for VssError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VssError::NullAsync => write!(f, "VSS async handle was null"),
+            VssError::Failed(hr) => write!(f, "{}", describe_hresult(*hr)),
+            VssError::WriterFailed {
+                writer_id,
+                writer_name,
+                state,
+                reason,
+            } => write!(
+                f,
+                "writer {} ({}) is {}: {}",
+                writer_name, writer_id, state, reason
+            ),
+            VssError::NoMatchingSnapshot(path) => {
+                write!(f, "no snapshot covers the volume of path '{}'", path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VssError {}
+
 /// Converts a GUID to a String
 fn guid_to_string(guid: &GUID) -> String {
     let mut buffer: [u16; 39] = [0; 39]; // GUID string is 38 chars + null terminator
@@ -32,114 +254,141 @@ fn guid_to_string(guid: &GUID) -> String {
 
 /// Struct to hold details of VSS writers
 struct WriterDetails {
+    pub instance_id: String,
     pub writer_id: String,
     pub writer_name: String,
+    pub state: VssWriterState,
+    pub failure_reason: VssWriterFailure,
 }
 
-fn wait_for_async(p_async: *mut IVssAsync) -> HRESULT {
+fn wait_for_async(p_async: *mut IVssAsync) -> Result<(), VssError> {
     unsafe {
         if p_async.is_null() {
             eprintln!("VSS async is null.");
-            return E_POINTER;
+            return Err(VssError::NullAsync);
         }
 
         // Call Wait() to wait for the operation to complete
         let mut hr = (*p_async).Wait(INFINITE);
         if FAILED(hr) {
-            eprintln!("Wait failed with error: {}", hresult_to_hex(hr));
-            return hr;
+            eprintln!("Wait failed with error: {}", describe_hresult(hr));
+            return Err(VssError::Failed(hr));
         }
 
         // Query the status of the async operation
         let mut hr_status: HRESULT = 0;
         hr = (*p_async).QueryStatus(&mut hr_status, ptr::null_mut());
 
-        if FAILED(hr_status) {
-            eprintln!("QueryStatus failed with error: {}", hresult_to_hex(hr));
-            return hr_status;
+        if FAILED(hr) {
+            eprintln!("QueryStatus failed with error: {}", describe_hresult(hr));
+            return Err(VssError::Failed(hr));
+        }
+
+        if FAILED(hr_status) || hr_status == VSS_S_ASYNC_CANCELLED {
+            eprintln!(
+                "Async operation did not complete successfully: {}",
+                describe_hresult(hr_status)
+            );
+            return Err(VssError::Failed(hr_status));
         }
 
         // Return the final operation status
-        eprintln!("Result: {}", hresult_to_hex(hr_status));
-        hr_status
+        eprintln!("Result: {}", describe_hresult(hr_status));
+        Ok(())
     }
 }
 
-fn list_vss_writers() -> Vec<WriterDetails> {
-    unsafe {
-        // Initialize COM
-        let mut hr = CoInitializeEx(ptr::null_mut(), COINIT_APARTMENTTHREADED);
-        if hr != S_OK {
-            eprintln!("CoInit failed with error: {}", hresult_to_hex(hr));
-            return Vec::new();
-        }
-
-        // Create VSS Backup Components
-        let mut p_vss: *mut IVssBackupComponents = ptr::null_mut();
-        hr = CreateVssBackupComponents(&mut p_vss);
-        if hr != S_OK || p_vss.is_null() {
+/// Initializes COM, creates and initializes `IVssBackupComponents` for a
+/// full backup, and gathers writer metadata — the setup every entry point
+/// into this crate needs before it can do anything else. On failure, any
+/// partially-initialized COM state is released/uninitialized before
+/// returning the error.
+unsafe fn init_backup_components() -> Result<*mut IVssBackupComponents, VssError> {
+    let mut hr = CoInitializeEx(ptr::null_mut(), COINIT_APARTMENTTHREADED);
+    if hr != S_OK {
+        if SUCCEEDED(hr) {
             CoUninitialize();
-            eprintln!(
-                "CreateVssBackupComponents failed with error: {}",
-                hresult_to_hex(hr)
-            );
-            return Vec::new();
         }
+        eprintln!("CoInit failed with error: {}", describe_hresult(hr));
+        return Err(VssError::Failed(hr));
+    }
 
-        // Initialize the backup components
-        hr = (*p_vss).InitializeForBackup(ptr::null_mut());
-        if hr != S_OK {
-            (*p_vss).Release();
-            CoUninitialize();
-            eprintln!(
-                "Initialize for backup failed with error: {}",
-                hresult_to_hex(hr)
-            );
-            return Vec::new();
-        }
+    let mut p_vss: *mut IVssBackupComponents = ptr::null_mut();
+    hr = CreateVssBackupComponents(&mut p_vss);
+    if hr != S_OK || p_vss.is_null() {
+        CoUninitialize();
+        eprintln!(
+            "CreateVssBackupComponents failed with error: {}",
+            describe_hresult(hr)
+        );
+        return Err(VssError::Failed(hr));
+    }
 
-        hr = (*p_vss).SetBackupState(false, true, VSS_BT_FULL, false);
-        if FAILED(hr) {
-            (*p_vss).Release();
-            CoUninitialize();
-            eprintln!(
-                "Failed to set backup state with error: {}",
-                hresult_to_hex(hr)
-            );
-            return Vec::new();
-        }
+    hr = (*p_vss).InitializeForBackup(ptr::null_mut());
+    if hr != S_OK {
+        (*p_vss).Release();
+        CoUninitialize();
+        eprintln!(
+            "Initialize for backup failed with error: {}",
+            describe_hresult(hr)
+        );
+        return Err(VssError::Failed(hr));
+    }
 
-        // Gather writer metadata
-        let mut m_vss_sync: *mut IVssAsync = ptr::null_mut();
-        hr = (*p_vss).GatherWriterMetadata(&mut m_vss_sync);
-        if hr != S_OK {
-            (*p_vss).Release();
-            CoUninitialize();
-            eprintln!(
-                "GatherWriterMetadata failed with error: {}",
-                hresult_to_hex(hr)
-            );
-            return Vec::new();
-        }
+    hr = (*p_vss).SetBackupState(false, true, VSS_BT_FULL, false);
+    if FAILED(hr) {
+        (*p_vss).Release();
+        CoUninitialize();
+        eprintln!(
+            "Failed to set backup state with error: {}",
+            describe_hresult(hr)
+        );
+        return Err(VssError::Failed(hr));
+    }
 
-        // Wait for operation to complete
-        hr = wait_for_async(m_vss_sync);
-        if FAILED(hr) {
-            (*p_vss).Release();
-            CoUninitialize();
-            eprintln!("wait_for_async failed with error: {}", hresult_to_hex(hr));
-            return Vec::new();
-        }
+    // Gather writer metadata
+    let mut m_vss_sync: *mut IVssAsync = ptr::null_mut();
+    hr = (*p_vss).GatherWriterMetadata(&mut m_vss_sync);
+    if hr != S_OK {
+        (*p_vss).Release();
+        CoUninitialize();
+        eprintln!(
+            "GatherWriterMetadata failed with error: {}",
+            describe_hresult(hr)
+        );
+        return Err(VssError::Failed(hr));
+    }
+
+    // Wait for operation to complete
+    if let Err(e) = wait_for_async(m_vss_sync) {
+        (*p_vss).Release();
+        CoUninitialize();
+        eprintln!("wait_for_async failed: {}", e);
+        return Err(e);
+    }
+
+    Ok(p_vss)
+}
+
+fn list_vss_writers() -> Vec<WriterDetails> {
+    unsafe {
+        let p_vss = match init_backup_components() {
+            Ok(p_vss) => p_vss,
+            Err(e) => {
+                eprintln!("Failed to initialize backup components: {}", e);
+                return Vec::new();
+            }
+        };
 
         // Get writer status count
         let mut writer_count = 0;
-        hr = (*p_vss).GetWriterStatusCount(&mut writer_count);
+        let hr = (*p_vss).GetWriterStatusCount(&mut writer_count);
         if FAILED(hr) {
             (*p_vss).Release();
             CoUninitialize();
             eprintln!(
                 "GetWriterStatusCount failed with error: {}",
-                hresult_to_hex(hr)
+                describe_hresult(hr)
             );
             return Vec::new();
         }
@@ -175,11 +424,15 @@ fn list_vss_writers() -> Vec<WriterDetails> {
             ) == S_OK
             {
                 let writer_id_str = guid_to_string(&writer_id);
-                let writer_name_str = format!("{:?}", writer_name);
+                let writer_name_str = pwsz_to_string(writer_name);
+                CoTaskMemFree(writer_name as *mut _);
 
                 writers.push(WriterDetails {
+                    instance_id: guid_to_string(&instance_id),
                     writer_id: writer_id_str,
                     writer_name: writer_name_str,
+                    state: VssWriterState::from_raw(state),
+                    failure_reason: VssWriterFailure::from_raw(failure_reason),
                 });
             }
         }
@@ -193,11 +446,755 @@ fn list_vss_writers() -> Vec<WriterDetails> {
     }
 }
 
+/// Returns an error if any writer is in a failed state, so a backup caller
+/// can refuse to proceed while e.g. a `SqlServerWriter` is unstable.
+fn check_writer_status(writers: &[WriterDetails]) -> Result<(), VssError> {
+    for writer in writers {
+        if writer.state.is_failed() {
+            return Err(VssError::WriterFailed {
+                writer_id: writer.writer_id.clone(),
+                writer_name: writer.writer_name.clone(),
+                state: writer.state,
+                reason: writer.failure_reason,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// The null/zero GUID, used where VSS accepts `GUID_NULL` as a wildcard
+/// (e.g. "let the default provider handle this volume").
+const GUID_NULL: GUID = GUID {
+    Data1: 0,
+    Data2: 0,
+    Data3: 0,
+    Data4: [0; 8],
+};
+
+/// Encodes a Rust string as a null-terminated UTF-16 buffer suitable for
+/// passing to Win32/VSS APIs expecting a `VSS_PWSZ`/`LPCWSTR`.
+fn to_wide_null(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+/// Normalizes a path's drive letter into the canonical volume-root form VSS
+/// uses, e.g. `"c:\Users\a.txt"` and `"C:\"` both become `"C:\"`. Returns
+/// `None` if `path` isn't actually rooted at a drive letter — that includes
+/// drive-relative paths like `"C:Users\a.txt"` (no separator after the
+/// colon), which name a different current directory per drive and aren't a
+/// volume root at all.
+fn normalize_volume_root(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    if bytes.len() >= 3
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && bytes[2] == b'\\'
+    {
+        Some(format!("{}:\\", (bytes[0] as char).to_ascii_uppercase()))
+    } else {
+        None
+    }
+}
+
+/// Finds the snapshot whose volume matches `volume_root` (already the output
+/// of [`normalize_volume_root`]) among `snapshots`, the `(volume_path,
+/// snapshot_id)` pairs recorded by [`SnapshotSession::add_volume`].
+fn find_snapshot_for_path(
+    snapshots: &[(String, GUID)],
+    volume_root: &str,
+    path: &str,
+) -> Result<GUID, VssError> {
+    snapshots
+        .iter()
+        .find(|(volume, _)| normalize_volume_root(volume).as_deref() == Some(volume_root))
+        .map(|(_, id)| *id)
+        .ok_or_else(|| VssError::NoMatchingSnapshot(path.to_string()))
+}
+
+/// Rewrites `path` onto `device_path`, the snapshot's device object path,
+/// dropping the `volume_root` prefix (e.g. `C:\`) and keeping the rest of the
+/// path relative to it.
+fn join_shadow_path(device_path: &str, volume_root: &str, path: &str) -> String {
+    let relative = path[volume_root.len().min(path.len())..].trim_start_matches('\\');
+    format!("{}\\{}", device_path.trim_end_matches('\\'), relative)
+}
+
+/// Reads a null-terminated `VSS_PWSZ` (or any other plain `*mut u16` wide
+/// string, e.g. from `FormatMessageW`) into an owned `String`. Returns
+/// `"(null)"` for a null pointer rather than panicking, since VSS freely
+/// hands back null strings for unset fields.
+unsafe fn pwsz_to_string(pwsz: *const u16) -> String {
+    if pwsz.is_null() {
+        return String::from("(null)");
+    }
+
+    let mut len = 0isize;
+    while *pwsz.offset(len) != 0 {
+        len += 1;
+    }
+
+    let slice = std::slice::from_raw_parts(pwsz, len as usize);
+    String::from_utf16_lossy(slice)
+}
+
+/// Reads a `BSTR` into an owned `String` using its length prefix (the `u32`
+/// byte length stored immediately before the pointer) rather than scanning
+/// for a null terminator, since a `BSTR` may legitimately contain embedded
+/// nulls. Returns `"(null)"` for a null pointer.
+unsafe fn bstr_to_string(bstr: *const u16) -> String {
+    if bstr.is_null() {
+        return String::from("(null)");
+    }
+
+    let byte_len = *(bstr as *const u32).offset(-1);
+    let slice = std::slice::from_raw_parts(bstr, (byte_len / 2) as usize);
+    String::from_utf16_lossy(slice)
+}
+
+/// Identity fields of a VSS writer, from `IVssExamineWriterMetadata::GetIdentity`.
+pub struct WriterIdentity {
+    pub instance_id: GUID,
+    pub writer_id: GUID,
+    pub writer_name: String,
+    pub usage: VSS_USAGE_TYPE,
+    pub source: VSS_SOURCE_TYPE,
+}
+
+/// A file (or file pattern) covered by a writer component, from
+/// `IVssWMFiledesc`.
+pub struct FileDescriptor {
+    pub path: String,
+    pub filespec: String,
+    pub recursive: bool,
+}
+
+/// A single backup component exposed by a writer, from `IVssWMComponent`.
+pub struct Component {
+    pub logical_path: String,
+    pub component_name: String,
+    pub component_type: VSS_COMPONENT_TYPE,
+    pub selectable: bool,
+    pub files: Vec<FileDescriptor>,
+}
+
+/// A writer's identity plus the components it offers for backup, returned by
+/// [`SnapshotSession::writer_metadata`].
+pub struct WriterMetadata {
+    pub identity: WriterIdentity,
+    pub components: Vec<Component>,
+}
+
+/// Metadata for a single shadow copy, decoded from `VSS_SNAPSHOT_PROP` and
+/// returned by [`SnapshotSession::query_snapshots`].
+pub struct SnapshotProperties {
+    pub snapshot_id: GUID,
+    pub snapshot_set_id: GUID,
+    pub original_volume_name: String,
+    pub snapshot_device_object: String,
+    pub provider_id: GUID,
+    pub creation_timestamp: i64,
+    pub attributes: i32,
+}
+
+/// The COM call (if any) that `SnapshotSession::finish` should make, given
+/// whether the session has already finished and whether `DoSnapshotSet`
+/// completed successfully. Pulled out as a pure decision so the state
+/// machine can be unit tested without a live `IVssBackupComponents`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FinishAction {
+    /// The session already finished; do nothing.
+    None,
+    /// `DoSnapshotSet` never completed successfully; call `AbortBackup`.
+    Abort,
+    /// The snapshot set was created; call `BackupComplete`.
+    Complete,
+}
+
+fn finish_action(already_finished: bool, succeeded: bool) -> FinishAction {
+    if already_finished {
+        FinishAction::None
+    } else if succeeded {
+        FinishAction::Complete
+    } else {
+        FinishAction::Abort
+    }
+}
+
+/// Drives a single VSS snapshot-set through its full lifecycle: starting the
+/// set, adding volumes to it, preparing for backup, and committing the
+/// snapshot. Dropping (or explicitly [`abort`](SnapshotSession::abort)ing) an
+/// unfinished session tells VSS the backup is over so it doesn't leave
+/// orphaned shadow copies behind.
+struct SnapshotSession {
+    p_vss: *mut IVssBackupComponents,
+    snapshot_set_id: GUID,
+    /// Volume path -> snapshot id, in the order volumes were added.
+    snapshots: Vec<(String, GUID)>,
+    finished: bool,
+    /// Whether `DoSnapshotSet` has completed successfully. Drives the
+    /// `BackupComplete` vs `AbortBackup` choice in `finish`: a session that
+    /// never got this far (including one dropped by the `?` operator after
+    /// a failed `add_volume`/`prepare_for_backup`/`do_snapshot_set`) has
+    /// nothing to commit and should be aborted, not reported as succeeded.
+    succeeded: bool,
+}
+
+impl SnapshotSession {
+    /// Initializes COM and VSS, gathers writer metadata, and starts a new
+    /// snapshot set. The returned session owns the `IVssBackupComponents`
+    /// instance until it is finished or dropped.
+    pub fn start() -> Result<Self, VssError> {
+        unsafe {
+            let p_vss = init_backup_components()?;
+
+            let mut snapshot_set_id = GUID_NULL;
+            let hr = (*p_vss).StartSnapshotSet(&mut snapshot_set_id);
+            if FAILED(hr) {
+                (*p_vss).Release();
+                CoUninitialize();
+                eprintln!("StartSnapshotSet failed with error: {}", describe_hresult(hr));
+                return Err(VssError::Failed(hr));
+            }
+
+            Ok(SnapshotSession {
+                p_vss,
+                snapshot_set_id,
+                snapshots: Vec::new(),
+                finished: false,
+                succeeded: false,
+            })
+        }
+    }
+
+    /// Returns the `GUID` identifying this snapshot set.
+    pub fn snapshot_set_id(&self) -> GUID {
+        self.snapshot_set_id
+    }
+
+    /// Adds a volume (e.g. `"C:\\"`) to the snapshot set and returns the
+    /// per-volume snapshot id assigned by VSS.
+    pub fn add_volume(&mut self, volume_path: &str) -> Result<GUID, VssError> {
+        unsafe {
+            let wide_path = to_wide_null(volume_path);
+            let mut snapshot_id = GUID_NULL;
+            let hr = (*self.p_vss).AddToSnapshotSet(
+                wide_path.as_ptr() as VSS_PWSZ,
+                GUID_NULL,
+                &mut snapshot_id,
+            );
+            if FAILED(hr) {
+                eprintln!("AddToSnapshotSet failed with error: {}", describe_hresult(hr));
+                return Err(VssError::Failed(hr));
+            }
+
+            self.snapshots.push((volume_path.to_string(), snapshot_id));
+            Ok(snapshot_id)
+        }
+    }
+
+    /// Calls `PrepareForBackup` and waits for it to finish.
+    pub fn prepare_for_backup(&mut self) -> Result<(), VssError> {
+        unsafe {
+            let mut p_async: *mut IVssAsync = ptr::null_mut();
+            let hr = (*self.p_vss).PrepareForBackup(&mut p_async);
+            if FAILED(hr) {
+                eprintln!("PrepareForBackup failed with error: {}", describe_hresult(hr));
+                return Err(VssError::Failed(hr));
+            }
+
+            wait_for_async(p_async)
+        }
+    }
+
+    /// Calls `DoSnapshotSet` and waits for the snapshots to be created.
+    pub fn do_snapshot_set(&mut self) -> Result<(), VssError> {
+        unsafe {
+            let mut p_async: *mut IVssAsync = ptr::null_mut();
+            let hr = (*self.p_vss).DoSnapshotSet(&mut p_async);
+            if FAILED(hr) {
+                eprintln!("DoSnapshotSet failed with error: {}", describe_hresult(hr));
+                return Err(VssError::Failed(hr));
+            }
+
+            wait_for_async(p_async)?;
+            self.succeeded = true;
+            Ok(())
+        }
+    }
+
+    /// Returns the device object path of a snapshot created in this set,
+    /// e.g. `\\?\GLOBALROOT\Device\HarddiskVolumeShadowCopy1`.
+    pub fn device_path_for(&self, snapshot_id: &GUID) -> Result<String, VssError> {
+        unsafe {
+            let mut props: VSS_SNAPSHOT_PROP = std::mem::zeroed();
+            let hr = (*self.p_vss).GetSnapshotProperties(*snapshot_id, &mut props);
+            if FAILED(hr) {
+                eprintln!(
+                    "GetSnapshotProperties failed with error: {}",
+                    describe_hresult(hr)
+                );
+                return Err(VssError::Failed(hr));
+            }
+
+            let device_path = pwsz_to_string(props.m_pwszSnapshotDeviceObject);
+            VssFreeSnapshotProperties(&mut props);
+            Ok(device_path)
+        }
+    }
+
+    /// Returns the volumes added so far as `(volume_path, snapshot_id)`; the
+    /// shadow copies themselves exist only once `do_snapshot_set` succeeds.
+    pub fn snapshots(&self) -> &[(String, GUID)] {
+        &self.snapshots
+    }
+
+    /// Rewrites a normal filesystem path onto its snapshot equivalent, so
+    /// callers can open the file as it was at snapshot time rather than on
+    /// the live volume, e.g. `C:\Users\a.txt` becomes
+    /// `\\?\GLOBALROOT\Device\HarddiskVolumeShadowCopy1\Users\a.txt`.
+    ///
+    /// Fails with [`VssError::NoMatchingSnapshot`] if `path` isn't rooted at
+    /// a drive letter this session took a snapshot of.
+    pub fn shadow_path_for(&self, path: &str) -> Result<String, VssError> {
+        let volume_root = normalize_volume_root(path)
+            .ok_or_else(|| VssError::NoMatchingSnapshot(path.to_string()))?;
+        let snapshot_id = find_snapshot_for_path(&self.snapshots, &volume_root, path)?;
+        let device_path = self.device_path_for(&snapshot_id)?;
+
+        Ok(join_shadow_path(&device_path, &volume_root, path))
+    }
+
+    /// Enumerates shadow copies already present on the system via
+    /// `IVssBackupComponents::Query`, regardless of whether this session
+    /// created them. Useful for inspecting snapshots left behind by other
+    /// backup tools.
+    pub fn query_snapshots(&self) -> Result<Vec<SnapshotProperties>, VssError> {
+        unsafe {
+            let mut p_enum: *mut IVssEnumObject = ptr::null_mut();
+            let hr = (*self.p_vss).Query(
+                GUID_NULL,
+                VSS_OBJECT_NONE,
+                VSS_OBJECT_SNAPSHOT,
+                &mut p_enum,
+            );
+            if FAILED(hr) {
+                eprintln!("Query failed with error: {}", describe_hresult(hr));
+                return Err(VssError::Failed(hr));
+            }
+
+            let mut snapshots = Vec::new();
+            loop {
+                let mut prop: VSS_OBJECT_PROP = std::mem::zeroed();
+                let mut fetched: u32 = 0;
+                let hr = (*p_enum).Next(1, &mut prop, &mut fetched);
+                if hr == S_FALSE || fetched == 0 {
+                    break;
+                }
+                if FAILED(hr) {
+                    eprintln!(
+                        "IVssEnumObject::Next failed with error: {}",
+                        describe_hresult(hr)
+                    );
+                    (*p_enum).Release();
+                    return Err(VssError::Failed(hr));
+                }
+
+                let mut snap = prop.Obj.Snap;
+                snapshots.push(SnapshotProperties {
+                    snapshot_id: snap.m_SnapshotId,
+                    snapshot_set_id: snap.m_SnapshotSetId,
+                    original_volume_name: pwsz_to_string(snap.m_pwszOriginalVolumeName),
+                    snapshot_device_object: pwsz_to_string(snap.m_pwszSnapshotDeviceObject),
+                    provider_id: snap.m_ProviderId,
+                    creation_timestamp: snap.m_tCreationTimestamp,
+                    attributes: snap.m_lSnapshotAttributes,
+                });
+
+                VssFreeSnapshotProperties(&mut snap);
+            }
+
+            (*p_enum).Release();
+            Ok(snapshots)
+        }
+    }
+
+    /// Gathers per-writer component and file-set metadata via
+    /// `IVssExamineWriterMetadata`, so callers can decide which components
+    /// to include in a backup. Requires that metadata has already been
+    /// gathered (it is, as part of [`start`](SnapshotSession::start)).
+    pub fn writer_metadata(&self) -> Result<Vec<WriterMetadata>, VssError> {
+        unsafe {
+            let mut writer_count: u32 = 0;
+            let hr = (*self.p_vss).GetWriterMetadataCount(&mut writer_count);
+            if FAILED(hr) {
+                eprintln!(
+                    "GetWriterMetadataCount failed with error: {}",
+                    describe_hresult(hr)
+                );
+                return Err(VssError::Failed(hr));
+            }
+
+            let mut writers = Vec::new();
+            for i in 0..writer_count {
+                let mut writer_id = GUID_NULL;
+                let mut p_metadata: *mut IVssExamineWriterMetadata = ptr::null_mut();
+                let hr = (*self.p_vss).GetWriterMetadata(i, &mut writer_id, &mut p_metadata);
+                if FAILED(hr) {
+                    eprintln!("GetWriterMetadata failed with error: {}", describe_hresult(hr));
+                    continue;
+                }
+
+                let mut instance_id = GUID_NULL;
+                let mut writer_id2 = GUID_NULL;
+                let mut writer_name: BSTR = ptr::null_mut();
+                let mut usage: VSS_USAGE_TYPE = 0;
+                let mut source: VSS_SOURCE_TYPE = 0;
+                let hr = (*p_metadata).GetIdentity(
+                    &mut instance_id,
+                    &mut writer_id2,
+                    &mut writer_name,
+                    &mut usage,
+                    &mut source,
+                );
+                if FAILED(hr) {
+                    eprintln!("GetIdentity failed with error: {}", describe_hresult(hr));
+                    (*p_metadata).Release();
+                    continue;
+                }
+                let identity = WriterIdentity {
+                    instance_id,
+                    writer_id: writer_id2,
+                    writer_name: bstr_to_string(writer_name),
+                    usage,
+                    source,
+                };
+                SysFreeString(writer_name);
+
+                let mut component_count: u32 = 0;
+                let hr = (*p_metadata).GetComponentCount(&mut component_count);
+                if FAILED(hr) {
+                    eprintln!("GetComponentCount failed with error: {}", describe_hresult(hr));
+                    (*p_metadata).Release();
+                    continue;
+                }
+
+                let mut components = Vec::new();
+                for c in 0..component_count {
+                    let mut p_component: *mut IVssWMComponent = ptr::null_mut();
+                    let hr = (*p_metadata).GetComponent(c, &mut p_component);
+                    if FAILED(hr) {
+                        eprintln!("GetComponent failed with error: {}", describe_hresult(hr));
+                        continue;
+                    }
+
+                    let mut info: PVSSCOMPONENTINFO = ptr::null_mut();
+                    let hr = (*p_component).GetComponentInfo(&mut info);
+                    if FAILED(hr) {
+                        eprintln!("GetComponentInfo failed with error: {}", describe_hresult(hr));
+                        (*p_component).Release();
+                        continue;
+                    }
+
+                    let mut files = Vec::new();
+                    for f in 0..(*info).cFileCount {
+                        let mut p_file: *mut IVssWMFiledesc = ptr::null_mut();
+                        let hr = (*p_component).GetFile(f, &mut p_file);
+                        if FAILED(hr) {
+                            eprintln!("GetFile failed with error: {}", describe_hresult(hr));
+                            continue;
+                        }
+
+                        let mut path: BSTR = ptr::null_mut();
+                        let hr = (*p_file).GetPath(&mut path);
+                        if FAILED(hr) {
+                            eprintln!("GetPath failed with error: {}", describe_hresult(hr));
+                            (*p_file).Release();
+                            continue;
+                        }
+                        let mut filespec: BSTR = ptr::null_mut();
+                        let hr = (*p_file).GetFilespec(&mut filespec);
+                        if FAILED(hr) {
+                            eprintln!("GetFilespec failed with error: {}", describe_hresult(hr));
+                            SysFreeString(path);
+                            (*p_file).Release();
+                            continue;
+                        }
+                        let mut recursive = false;
+                        let hr = (*p_file).GetRecursive(&mut recursive);
+                        if FAILED(hr) {
+                            eprintln!("GetRecursive failed with error: {}", describe_hresult(hr));
+                            SysFreeString(path);
+                            SysFreeString(filespec);
+                            (*p_file).Release();
+                            continue;
+                        }
+
+                        files.push(FileDescriptor {
+                            path: bstr_to_string(path),
+                            filespec: bstr_to_string(filespec),
+                            recursive,
+                        });
+
+                        SysFreeString(path);
+                        SysFreeString(filespec);
+                        (*p_file).Release();
+                    }
+
+                    components.push(Component {
+                        logical_path: bstr_to_string((*info).bstrLogicalPath),
+                        component_name: bstr_to_string((*info).bstrComponentName),
+                        component_type: (*info).Type,
+                        selectable: (*info).bSelectable != 0,
+                        files,
+                    });
+
+                    (*p_component).FreeComponentInfo(info);
+                    (*p_component).Release();
+                }
+
+                writers.push(WriterMetadata { identity, components });
+                (*p_metadata).Release();
+            }
+
+            Ok(writers)
+        }
+    }
+
+    /// Tells VSS the backup is over and releases the backup components
+    /// instance, so no shadow copies are left orphaned. Calls
+    /// `BackupComplete` if `succeeded` (the snapshot set was actually
+    /// created) or `AbortBackup` otherwise, since `BackupComplete` is only
+    /// meaningful once `DoSnapshotSet` has gone through.
+    fn finish(&mut self, succeeded: bool) {
+        let action = finish_action(self.finished, succeeded);
+        if action == FinishAction::None {
+            return;
+        }
+        self.finished = true;
+
+        unsafe {
+            match action {
+                FinishAction::None => unreachable!(),
+                FinishAction::Complete => {
+                    let hr = (*self.p_vss).BackupComplete(ptr::null_mut());
+                    if FAILED(hr) {
+                        eprintln!("BackupComplete failed with error: {}", describe_hresult(hr));
+                    }
+                }
+                FinishAction::Abort => {
+                    let hr = (*self.p_vss).AbortBackup();
+                    if FAILED(hr) {
+                        eprintln!("AbortBackup failed with error: {}", describe_hresult(hr));
+                    }
+                }
+            }
+
+            (*self.p_vss).Release();
+            CoUninitialize();
+        }
+    }
+
+    /// Explicitly aborts the session, calling `AbortBackup` and releasing
+    /// the backup components instance immediately rather than waiting for
+    /// `Drop`.
+    pub fn abort(mut self) {
+        self.finish(false);
+    }
+}
+
+impl Drop for SnapshotSession {
+    fn drop(&mut self) {
+        let succeeded = self.succeeded;
+        self.finish(succeeded);
+    }
+}
+
 fn main() {
     let writers = list_vss_writers();
     println!("List of VSS Writers:");
-    for writer in writers {
+    for writer in &writers {
         println!("Id: {}", writer.writer_id);
         println!("Name: {}", writer.writer_name);
+        println!("State: {}", writer.state);
+    }
+
+    if let Err(e) = check_writer_status(&writers) {
+        eprintln!("Refusing to proceed: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finish_action_aborts_when_never_succeeded() {
+        assert_eq!(finish_action(false, false), FinishAction::Abort);
+    }
+
+    #[test]
+    fn finish_action_completes_when_succeeded() {
+        assert_eq!(finish_action(false, true), FinishAction::Complete);
+    }
+
+    #[test]
+    fn finish_action_is_idempotent_once_finished() {
+        // Once finished, neither a later `abort()` nor the `Drop` path
+        // should make a second COM call, regardless of `succeeded`.
+        assert_eq!(finish_action(true, false), FinishAction::None);
+        assert_eq!(finish_action(true, true), FinishAction::None);
+    }
+
+    #[test]
+    fn describe_hresult_formats_known_async_status() {
+        let description = describe_hresult(VSS_S_ASYNC_CANCELLED);
+        assert!(description.contains("cancelled"));
+        assert!(description.contains(&hresult_to_hex(VSS_S_ASYNC_CANCELLED)));
+    }
+
+    #[test]
+    fn pwsz_to_string_decodes_null_terminated_buffer() {
+        let buffer: Vec<u16> = "hello".encode_utf16().chain(std::iter::once(0)).collect();
+        let decoded = unsafe { pwsz_to_string(buffer.as_ptr()) };
+        assert_eq!(decoded, "hello");
+    }
+
+    #[test]
+    fn pwsz_to_string_handles_null_pointer() {
+        let decoded = unsafe { pwsz_to_string(ptr::null()) };
+        assert_eq!(decoded, "(null)");
+    }
+
+    #[test]
+    fn bstr_to_string_decodes_length_prefixed_buffer() {
+        let text: Vec<u16> = "hi".encode_utf16().collect();
+        let byte_len = (text.len() * 2) as u32;
+        // Lay out [len: u32][chars...] and hand back a pointer just past the
+        // prefix, matching how a real BSTR is addressed.
+        let mut buffer: Vec<u16> = vec![0, 0];
+        buffer[0] = (byte_len & 0xFFFF) as u16;
+        buffer[1] = (byte_len >> 16) as u16;
+        buffer.extend_from_slice(&text);
+
+        let bstr_ptr = unsafe { buffer.as_ptr().offset(2) };
+        let decoded = unsafe { bstr_to_string(bstr_ptr) };
+        assert_eq!(decoded, "hi");
+    }
+
+    #[test]
+    fn bstr_to_string_handles_null_pointer() {
+        let decoded = unsafe { bstr_to_string(ptr::null()) };
+        assert_eq!(decoded, "(null)");
+    }
+
+    #[test]
+    fn writer_state_from_raw_round_trips_known_states() {
+        assert_eq!(VssWriterState::from_raw(VSS_WS_STABLE), VssWriterState::Stable);
+        assert_eq!(
+            VssWriterState::from_raw(VSS_WS_FAILED_AT_FREEZE),
+            VssWriterState::FailedAtFreeze
+        );
+        assert_eq!(VssWriterState::from_raw(12345), VssWriterState::Other(12345));
+    }
+
+    #[test]
+    fn writer_state_is_failed_covers_only_failed_states() {
+        assert!(VssWriterState::FailedAtFreeze.is_failed());
+        assert!(VssWriterState::FailedAtBackupShutdown.is_failed());
+        assert!(!VssWriterState::Stable.is_failed());
+        assert!(!VssWriterState::WaitingForFreeze.is_failed());
+        assert!(!VssWriterState::Other(0).is_failed());
+    }
+
+    #[test]
+    fn writer_failure_from_raw_maps_known_codes() {
+        assert_eq!(VssWriterFailure::from_raw(S_OK), VssWriterFailure::None);
+        assert_eq!(
+            VssWriterFailure::from_raw(VSS_E_WRITERERROR_TIMEOUT),
+            VssWriterFailure::Timeout
+        );
+        assert_eq!(
+            VssWriterFailure::from_raw(E_FAIL),
+            VssWriterFailure::Other(E_FAIL)
+        );
+    }
+
+    #[test]
+    fn normalize_volume_root_accepts_drive_root() {
+        assert_eq!(
+            normalize_volume_root("c:\\Users\\a.txt"),
+            Some("C:\\".to_string())
+        );
+        assert_eq!(normalize_volume_root("D:\\"), Some("D:\\".to_string()));
+    }
+
+    #[test]
+    fn normalize_volume_root_rejects_drive_relative_paths() {
+        // "C:Users\a.txt" has no separator after the colon, so it names a
+        // path relative to C:'s current directory, not the volume root.
+        assert_eq!(normalize_volume_root("C:Users\\a.txt"), None);
+        assert_eq!(normalize_volume_root("C:"), None);
+    }
+
+    #[test]
+    fn normalize_volume_root_rejects_non_drive_paths() {
+        assert_eq!(normalize_volume_root("\\\\server\\share\\a.txt"), None);
+        assert_eq!(normalize_volume_root("relative\\a.txt"), None);
+        assert_eq!(normalize_volume_root(""), None);
+    }
+
+    fn guid_from_u32(value: u32) -> GUID {
+        GUID {
+            Data1: value,
+            Data2: 0,
+            Data3: 0,
+            Data4: [0; 8],
+        }
+    }
+
+    #[test]
+    fn find_snapshot_for_path_matches_case_insensitively() {
+        let snapshots = vec![
+            ("c:\\".to_string(), guid_from_u32(1)),
+            ("D:\\".to_string(), guid_from_u32(2)),
+        ];
+
+        let found = find_snapshot_for_path(&snapshots, "C:\\", "C:\\Users\\a.txt").unwrap();
+        assert_eq!(found, guid_from_u32(1));
+    }
+
+    #[test]
+    fn find_snapshot_for_path_errors_when_no_volume_matches() {
+        let snapshots = vec![("c:\\".to_string(), guid_from_u32(1))];
+
+        let err = find_snapshot_for_path(&snapshots, "E:\\", "E:\\a.txt").unwrap_err();
+        assert!(matches!(err, VssError::NoMatchingSnapshot(path) if path == "E:\\a.txt"));
+    }
+
+    #[test]
+    fn join_shadow_path_rewrites_onto_device_path() {
+        let result = join_shadow_path(
+            "\\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy1\\",
+            "C:\\",
+            "C:\\Users\\a.txt",
+        );
+        assert_eq!(
+            result,
+            "\\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy1\\Users\\a.txt"
+        );
+    }
+
+    #[test]
+    fn join_shadow_path_handles_volume_root_itself() {
+        let result = join_shadow_path(
+            "\\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy1",
+            "C:\\",
+            "C:\\",
+        );
+        assert_eq!(
+            result,
+            "\\\\?\\GLOBALROOT\\Device\\HarddiskVolumeShadowCopy1\\"
+        );
     }
 }